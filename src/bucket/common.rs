@@ -2,9 +2,11 @@ use async_trait::async_trait;
 use futures::StreamExt;
 use mongodb::bson::oid::ObjectId;
 use mongodb::bson::{doc, Document};
-use mongodb_gridfs::options::{GridFSFindOptions, GridFSUploadOptions};
+use mongodb_gridfs::options::GridFSFindOptions;
 use mongodb_gridfs::GridFSBucket;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
+use crate::bucket::stream::{GridFSAsyncReader, GridFSAsyncWriter};
 use crate::error::{GridFSError, Result};
 
 /// Extend common helper methods to [GridFSBucket].
@@ -15,6 +17,17 @@ pub trait GridFSBucketExt {
     where
         S: AsRef<str> + Send;
 
+    /// Get doc id for a specific `revision` of `filename`, disambiguating files that
+    /// share the same name by `uploadDate`.
+    ///
+    /// A non-negative `revision` counts forward from the oldest upload (`0` = oldest); a
+    /// negative `revision` counts back from the newest (`-1` = latest, `-2` = second
+    /// newest). Returns [`crate::error::GridFSError::FileNotFound`] when the revision is
+    /// out of range.
+    async fn id_by_revision<S>(&self, filename: S, revision: i32) -> Result<ObjectId>
+    where
+        S: AsRef<str> + Send;
+
     /// Get doc by `id`.
     async fn find_one_by_id(&self, id: ObjectId) -> Result<Document>;
 
@@ -27,6 +40,9 @@ pub trait GridFSBucketExt {
     /// Get doc filename by `id`.
     async fn md5(&self, id: ObjectId) -> Result<String>;
 
+    /// Get the MIME type sniffed at upload time for doc `id`, if any was stored.
+    async fn content_type(&self, id: ObjectId) -> Result<Option<String>>;
+
     /// Read cloud file by `filename` as [alloc::String].
     async fn read_string<S>(&self, filename: S) -> Result<String>
     where
@@ -37,12 +53,35 @@ pub trait GridFSBucketExt {
     where
         S: AsRef<str> + Send;
 
+    /// Read a specific `revision` of cloud file `filename` as [alloc::String]. See
+    /// [`GridFSBucketExt::id_by_revision`].
+    async fn read_string_by_revision<S>(&self, filename: S, revision: i32) -> Result<String>
+    where
+        S: AsRef<str> + Send;
+
+    /// Read a specific `revision` of cloud file `filename` as [alloc::Vec<u8>]. See
+    /// [`GridFSBucketExt::id_by_revision`].
+    async fn read_bytes_by_revision<S>(&self, filename: S, revision: i32) -> Result<Vec<u8>>
+    where
+        S: AsRef<str> + Send;
+
     /// Read cloud file by `id` as [alloc::String].
     async fn read_string_by_id(&self, id: ObjectId) -> Result<String>;
 
     /// Read cloud file by `id` as [alloc::Vec<u8>].
     async fn read_bytes_by_id(&self, id: ObjectId) -> Result<Vec<u8>>;
 
+    /// Read the byte range `[start, end)` of the cloud file by `id`, without downloading
+    /// the whole file. Useful for serving HTTP `Range` requests or resuming interrupted
+    /// downloads.
+    async fn read_bytes_range_by_id(&self, id: ObjectId, start: u64, end: u64)
+        -> Result<Vec<u8>>;
+
+    /// Open a streaming reader over the cloud file `id`'s chunks, without buffering the
+    /// whole file in memory. Pipe it into `tokio::io::copy`, a response body, or a
+    /// hashing sink.
+    async fn open_async_read(&self, id: ObjectId) -> Result<GridFSAsyncReader>;
+
     /// Write [&str] into cloud file by `id`.
     async fn write_string<S>(&mut self, filename: S, content: &str) -> Result<()>
     where
@@ -55,6 +94,14 @@ pub trait GridFSBucketExt {
         content: &[u8],
     ) -> Result<()>;
 
+    /// Open a streaming writer that uploads `filename` as bytes are written to it,
+    /// without buffering the whole payload in memory. Call
+    /// [`GridFSAsyncWriter::finish`] once done writing to get the uploaded document's id.
+    async fn open_async_write<S: AsRef<str> + Send>(
+        &mut self,
+        filename: S,
+    ) -> Result<GridFSAsyncWriter>;
+
     /// Return true if there is a file on the cloud with `filename`.
     async fn exists<S: AsRef<str> + Send>(&self, filename: S) -> Result<bool>;
 }
@@ -78,6 +125,28 @@ impl GridFSBucketExt for GridFSBucket {
             .map_err(Into::into)
     }
 
+    async fn id_by_revision<S>(&self, filename: S, revision: i32) -> Result<ObjectId>
+    where
+        S: AsRef<str> + Send,
+    {
+        let (sort, skip) = revision_sort_and_skip(revision);
+        let opt = GridFSFindOptions {
+            sort: Some(sort),
+            skip: Some(skip),
+            ..Default::default()
+        };
+        self.find(doc! {"filename": filename.as_ref()}, opt)
+            .await?
+            .next()
+            .await
+            .ok_or(GridFSError::FileNotFound {
+                filename: Some(filename.as_ref().to_string()),
+                id: None,
+            })?
+            .map(|doc| doc.get_object_id("_id").unwrap())
+            .map_err(Into::into)
+    }
+
     async fn find_one_by_id(&self, id: ObjectId) -> Result<Document> {
         let opt = GridFSFindOptions::default();
         self.find(doc! {"_id": id}, opt)
@@ -112,6 +181,15 @@ impl GridFSBucketExt for GridFSBucket {
             .map_err(Into::into)
     }
 
+    async fn content_type(&self, id: ObjectId) -> Result<Option<String>> {
+        let doc = self.find_one_by_id(id).await?;
+        Ok(doc
+            .get_document("metadata")
+            .ok()
+            .and_then(|metadata| metadata.get_str("contentType").ok())
+            .map(str::to_owned))
+    }
+
     async fn read_string<S>(&self, filename: S) -> Result<String>
     where
         S: AsRef<str> + Send,
@@ -129,6 +207,23 @@ impl GridFSBucketExt for GridFSBucket {
         self.read_bytes_by_id(id).await
     }
 
+    async fn read_string_by_revision<S>(&self, filename: S, revision: i32) -> Result<String>
+    where
+        S: AsRef<str> + Send,
+    {
+        self.read_bytes_by_revision(filename, revision)
+            .await
+            .and_then(|bytes| std::io::read_to_string(bytes.as_slice()).map_err(|err| err.into()))
+    }
+
+    async fn read_bytes_by_revision<S>(&self, filename: S, revision: i32) -> Result<Vec<u8>>
+    where
+        S: AsRef<str> + Send,
+    {
+        let id = self.id_by_revision(filename, revision).await?;
+        self.read_bytes_by_id(id).await
+    }
+
     async fn read_string_by_id(&self, id: ObjectId) -> Result<String> {
         self.read_bytes_by_id(id)
             .await
@@ -136,14 +231,68 @@ impl GridFSBucketExt for GridFSBucket {
     }
 
     async fn read_bytes_by_id(&self, id: ObjectId) -> Result<Vec<u8>> {
-        let mut bytes = Vec::<u8>::new();
-        let mut cursor = self.open_download_stream(id).await?;
-        while let Some(buffer) = cursor.next().await {
-            bytes.extend(buffer);
-        }
+        let mut bytes = Vec::new();
+        self.open_async_read(id).await?.read_to_end(&mut bytes).await?;
         Ok(bytes)
     }
 
+    async fn read_bytes_range_by_id(
+        &self,
+        id: ObjectId,
+        start: u64,
+        end: u64,
+    ) -> Result<Vec<u8>> {
+        if start > end {
+            return Err(GridFSError::InvalidPartialDownloadRange { start, end }.into());
+        }
+
+        let file = self.find_one_by_id(id).await?;
+        let file_length = file.get_i64("length").unwrap() as u64;
+        let chunk_size = file.get_i32("chunkSize").unwrap() as u64;
+
+        if start > file_length || end > file_length {
+            let value = if end > file_length { end } else { start };
+            return Err(GridFSError::PartialDownloadRangeOutOfBounds { value, file_length }.into());
+        }
+        if start == end {
+            return Ok(Vec::new());
+        }
+
+        let first_chunk = (start / chunk_size) as i32;
+        let last_chunk = ((end - 1) / chunk_size) as i32;
+
+        let chunks = self
+            .database()
+            .collection::<Document>(&format!("{}.chunks", self.bucket_name()));
+        let find_opt = mongodb::options::FindOptions::builder()
+            .sort(doc! {"n": 1})
+            .build();
+        let mut cursor = chunks
+            .find(
+                doc! {
+                    "files_id": id,
+                    "n": {"$gte": first_chunk, "$lte": last_chunk},
+                },
+                find_opt,
+            )
+            .await?;
+
+        let mut bytes = Vec::with_capacity((end - start) as usize);
+        while let Some(chunk) = cursor.next().await {
+            let chunk = chunk?;
+            bytes.extend_from_slice(chunk.get_binary_generic("data").unwrap());
+        }
+
+        let skip = (start % chunk_size) as usize;
+        let take = (end - start) as usize;
+        Ok(bytes[skip..skip + take].to_vec())
+    }
+
+    async fn open_async_read(&self, id: ObjectId) -> Result<GridFSAsyncReader> {
+        let cursor = self.open_download_stream(id).await?;
+        Ok(GridFSAsyncReader::new(Box::pin(cursor)))
+    }
+
     async fn write_string<S>(&mut self, filename: S, content: &str) -> Result<()>
     where
         S: AsRef<str> + Send + Sync,
@@ -155,12 +304,22 @@ impl GridFSBucketExt for GridFSBucket {
     where
         S: AsRef<str> + Send + Sync,
     {
-        let opt = GridFSUploadOptions::default();
-        self.upload_from_stream(filename.as_ref(), content, Some(opt))
-            .await?;
+        let mut writer = self.open_async_write(filename.as_ref()).await?;
+        writer.write_all(content).await?;
+        writer.finish().await?;
         Ok(())
     }
 
+    async fn open_async_write<S>(&mut self, filename: S) -> Result<GridFSAsyncWriter>
+    where
+        S: AsRef<str> + Send,
+    {
+        Ok(GridFSAsyncWriter::new(
+            self.clone(),
+            filename.as_ref().to_owned(),
+        ))
+    }
+
     async fn exists<S>(&self, filename: S) -> Result<bool>
     where
         S: AsRef<str> + Send,
@@ -171,14 +330,29 @@ impl GridFSBucketExt for GridFSBucket {
     }
 }
 
+/// Translate a revision index into the `(sort, skip)` pair that picks it out of the
+/// `uploadDate`-ordered set of docs sharing a filename. `_id` is included as a secondary
+/// sort key, matching the primary direction, since `uploadDate` only has millisecond
+/// resolution and ties would otherwise make the ordering unstable.
+fn revision_sort_and_skip(revision: i32) -> (Document, u64) {
+    if revision >= 0 {
+        (doc! {"uploadDate": 1, "_id": 1}, revision as u64)
+    } else {
+        (doc! {"uploadDate": -1, "_id": -1}, (-revision - 1) as u64)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use fake::Fake;
     use mongodb::Client;
     use test_utilities::docker;
     use test_utilities::gridfs::{TempFile, TempFileFaker};
+    use tokio::io::AsyncWriteExt;
 
-    use crate::error::GridFSError::FileNotFound;
+    use crate::error::GridFSError::{
+        FileNotFound, InvalidPartialDownloadRange, PartialDownloadRangeOutOfBounds,
+    };
     use crate::error::GridFSExtError::GridFSError;
 
     use super::*;
@@ -270,6 +444,191 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_read_bytes_range_by_id() {
+        let filename = "some-filename.txt";
+
+        let handle = docker::Builder::new("mongo")
+            .bind_port_as_default(Some("0"), "27017")
+            .build_disposable()
+            .await;
+
+        let bucket = GridFSBucket::new(
+            Client::with_uri_str(handle.url())
+                .await
+                .unwrap()
+                .database("test_db"),
+            None,
+        );
+
+        let file = TempFileFaker::with_bucket(bucket.clone())
+            .len(200..400)
+            .name(filename.into())
+            .include_content(true)
+            .fake::<TempFile>();
+        let content = file.content.unwrap();
+
+        let range = bucket
+            .read_bytes_range_by_id(file.id, 10, 20)
+            .await
+            .unwrap();
+        assert_eq!(content[10..20].to_vec(), range);
+
+        match bucket
+            .read_bytes_range_by_id(file.id, 20, 10)
+            .await
+            .unwrap_err()
+        {
+            GridFSError(InvalidPartialDownloadRange { .. }) => (),
+            _ => assert!(
+                false,
+                "Should return error [GridFSError(InvalidPartialDownloadRange())]"
+            ),
+        }
+
+        match bucket
+            .read_bytes_range_by_id(file.id, 0, content.len() as u64 + 1)
+            .await
+            .unwrap_err()
+        {
+            GridFSError(PartialDownloadRangeOutOfBounds { .. }) => (),
+            _ => assert!(
+                false,
+                "Should return error [GridFSError(PartialDownloadRangeOutOfBounds())]"
+            ),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_bytes_sniffs_content_type() {
+        let filename = "some-image.png";
+
+        let handle = docker::Builder::new("mongo")
+            .bind_port_as_default(Some("0"), "27017")
+            .build_disposable()
+            .await;
+
+        let mut bucket = GridFSBucket::new(
+            Client::with_uri_str(handle.url())
+                .await
+                .unwrap()
+                .database("test_db"),
+            None,
+        );
+
+        let png_bytes = b"\x89PNG\r\n\x1a\nrest-of-file";
+        bucket.write_bytes(filename, png_bytes).await.unwrap();
+
+        let id = bucket.id(filename).await.unwrap();
+        assert_eq!(
+            bucket.content_type(id).await.unwrap(),
+            Some("image/png".to_owned())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_id_by_revision() {
+        let filename = "some-filename.txt";
+
+        let handle = docker::Builder::new("mongo")
+            .bind_port_as_default(Some("0"), "27017")
+            .build_disposable()
+            .await;
+
+        let mut bucket = GridFSBucket::new(
+            Client::with_uri_str(handle.url())
+                .await
+                .unwrap()
+                .database("test_db"),
+            None,
+        );
+
+        bucket.write_string(filename, "oldest").await.unwrap();
+        bucket.write_string(filename, "middle").await.unwrap();
+        bucket.write_string(filename, "newest").await.unwrap();
+
+        let oldest_id = bucket.id_by_revision(filename, 0).await.unwrap();
+        let newest_id = bucket.id_by_revision(filename, -1).await.unwrap();
+        let middle_id = bucket.id_by_revision(filename, 1).await.unwrap();
+        let middle_id_negative = bucket.id_by_revision(filename, -2).await.unwrap();
+
+        assert_eq!(middle_id, middle_id_negative);
+        assert_ne!(oldest_id, newest_id);
+        assert_eq!(
+            "oldest",
+            bucket.read_string_by_revision(filename, 0).await.unwrap()
+        );
+        assert_eq!(
+            "newest",
+            bucket
+                .read_string_by_revision(filename, -1)
+                .await
+                .unwrap()
+        );
+
+        match bucket.id_by_revision(filename, 3).await.unwrap_err() {
+            GridFSError(FileNotFound { .. }) => (),
+            _ => assert!(false, "Should return error [GridFSError(FileNotFound())]"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_open_async_read_and_write() {
+        let handle = docker::Builder::new("mongo")
+            .bind_port_as_default(Some("0"), "27017")
+            .build_disposable()
+            .await;
+
+        let mut bucket = GridFSBucket::new(
+            Client::with_uri_str(handle.url())
+                .await
+                .unwrap()
+                .database("test_db"),
+            None,
+        );
+
+        let filename = "streamed-filename.txt";
+        let content = b"some streamed content".repeat(1024);
+
+        let mut writer = bucket.open_async_write(filename).await.unwrap();
+        writer.write_all(&content).await.unwrap();
+        let oid = writer.finish().await.unwrap();
+
+        let mut reader = bucket.open_async_read(oid).await.unwrap();
+        let mut read_back = Vec::new();
+        reader.read_to_end(&mut read_back).await.unwrap();
+
+        assert_eq!(content, read_back);
+    }
+
+    #[tokio::test]
+    async fn test_open_async_write_sniffs_content_type() {
+        let handle = docker::Builder::new("mongo")
+            .bind_port_as_default(Some("0"), "27017")
+            .build_disposable()
+            .await;
+
+        let mut bucket = GridFSBucket::new(
+            Client::with_uri_str(handle.url())
+                .await
+                .unwrap()
+                .database("test_db"),
+            None,
+        );
+
+        let filename = "streamed-image.png";
+        let png_bytes = b"\x89PNG\r\n\x1a\nrest-of-file";
+
+        let mut writer = bucket.open_async_write(filename).await.unwrap();
+        writer.write_all(png_bytes).await.unwrap();
+        let oid = writer.finish().await.unwrap();
+
+        assert_eq!(
+            bucket.content_type(oid).await.unwrap(),
+            Some("image/png".to_owned())
+        );
+    }
+
     #[tokio::test]
     async fn test_exists() {
         let filename = "some-filename.txt";
@@ -1,31 +1,86 @@
-use std::path::Path;
+use std::future::Future;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use async_trait::async_trait;
 use futures::StreamExt;
 use mongodb::bson::doc;
 use mongodb::bson::oid::ObjectId;
+use mongodb_gridfs::options::GridFSUploadOptions;
 use mongodb_gridfs::GridFSBucket;
 use tokio::io::AsyncWriteExt;
 
 use crate::bucket::common::GridFSBucketExt;
-use crate::error::Result;
+use crate::bucket::mime::{self, SNIFF_LEN};
+use crate::error::{GridFSError, GridFSExtError, Result};
+
+/// Max attempts for transient-error retries in [`FileSync::upload_from_resumable`].
+const MAX_UPLOAD_ATTEMPTS: u32 = 5;
+
+/// Delay before the first retry in [`FileSync::upload_from_resumable`]; doubles on
+/// each subsequent attempt.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(200);
 
 /// Extend file operation-related methods to GridFSBucket.
 #[async_trait]
 pub trait FileSync {
     /// Download file with `filename` from the cloud to `local_path`.
+    ///
+    /// Fails with an `AlreadyExists`-kind IO error if `local_path` already exists, and
+    /// never leaves a partial or zero-length file behind if the download fails. Use
+    /// [`FileSync::download_to_with_options`] to opt into overwriting.
     async fn download_to(
         &self,
         filename: &str,
         local_path: impl AsRef<Path> + Send + Sync,
     ) -> Result<ObjectId>;
 
+    /// Like [`FileSync::download_to`], but lets the caller opt into truncating an
+    /// existing file at `local_path` via `overwrite`.
+    async fn download_to_with_options(
+        &self,
+        filename: &str,
+        local_path: impl AsRef<Path> + Send + Sync,
+        overwrite: bool,
+    ) -> Result<ObjectId>;
+
+    /// Like [`FileSync::download_to`], but selects a specific `revision` of `filename`.
+    /// See [`GridFSBucketExt::id_by_revision`].
+    async fn download_to_by_revision(
+        &self,
+        filename: &str,
+        revision: i32,
+        local_path: impl AsRef<Path> + Send + Sync,
+    ) -> Result<ObjectId>;
+
+    /// Download the byte range `[start, end)` of file with `filename` from the cloud to
+    /// `local_path`, without streaming the whole file.
+    async fn download_range_to(
+        &self,
+        filename: &str,
+        start: u64,
+        end: u64,
+        local_path: impl AsRef<Path> + Send + Sync,
+    ) -> Result<ObjectId>;
+
     /// Upload file at `local_path` to the cloud with `filename`.
     async fn upload_from(
         &mut self,
         filename: &str,
         local_path: impl AsRef<Path> + Send,
     ) -> Result<ObjectId>;
+
+    /// Like [`FileSync::upload_from`], but resilient to dropped connections: retries
+    /// transient Mongo errors with exponential backoff, then verifies the stored file's
+    /// `md5` against a digest of `local_path` computed up front. Returns
+    /// [`crate::error::GridFSError::ChecksumMismatch`] and deletes the uploaded document
+    /// if the digests don't match, so callers get a trustworthy end-to-end guarantee.
+    async fn upload_from_resumable(
+        &mut self,
+        filename: &str,
+        local_path: impl AsRef<Path> + Send,
+    ) -> Result<ObjectId>;
 }
 
 #[async_trait]
@@ -34,13 +89,46 @@ impl FileSync for GridFSBucket {
         &self,
         filename: &str,
         local_path: impl AsRef<Path> + Send + Sync,
+    ) -> Result<ObjectId> {
+        self.download_to_with_options(filename, local_path, false)
+            .await
+    }
+
+    async fn download_to_with_options(
+        &self,
+        filename: &str,
+        local_path: impl AsRef<Path> + Send + Sync,
+        overwrite: bool,
     ) -> Result<ObjectId> {
         let oid = self.id(filename).await?;
-        let mut file = tokio::fs::File::create(local_path).await?;
-        let mut cursor = self.open_download_stream(oid).await?;
-        while let Some(buffer) = cursor.next().await {
-            file.write_all(&buffer).await?;
-        }
+        download_id_to(self, oid, local_path.as_ref(), overwrite).await?;
+        Ok(oid)
+    }
+
+    async fn download_to_by_revision(
+        &self,
+        filename: &str,
+        revision: i32,
+        local_path: impl AsRef<Path> + Send + Sync,
+    ) -> Result<ObjectId> {
+        let oid = self.id_by_revision(filename, revision).await?;
+        download_id_to(self, oid, local_path.as_ref(), false).await?;
+        Ok(oid)
+    }
+
+    async fn download_range_to(
+        &self,
+        filename: &str,
+        start: u64,
+        end: u64,
+        local_path: impl AsRef<Path> + Send + Sync,
+    ) -> Result<ObjectId> {
+        let oid = self.id(filename).await?;
+        let bytes = self.read_bytes_range_by_id(oid, start, end).await?;
+        atomic_write(local_path.as_ref(), false, |temp_path| async move {
+            tokio::fs::write(temp_path, bytes).await.map_err(Into::into)
+        })
+        .await?;
         Ok(oid)
     }
 
@@ -49,11 +137,165 @@ impl FileSync for GridFSBucket {
         filename: &str,
         local_path: impl AsRef<Path> + Send,
     ) -> Result<ObjectId> {
-        let file = tokio::fs::File::open(local_path).await?.into_std().await;
+        let local_path = local_path.as_ref();
+        let mut file = tokio::fs::File::open(local_path).await?.into_std().await;
+
+        let mut buf = [0u8; SNIFF_LEN];
+        let n = file.read(&mut buf)?;
+        let content_type = mime::sniff(&buf[..n]);
+        file.seek(SeekFrom::Start(0))?;
+
+        let extension = local_path.extension().and_then(|ext| ext.to_str());
+        let opt = GridFSUploadOptions {
+            metadata: Some(mime::metadata_doc(content_type, extension)),
+            ..Default::default()
+        };
+
         let async_file = futures::io::AllowStdIo::new(file);
-        let oid = self.upload_from_stream(filename, async_file, None).await?;
+        let oid = self
+            .upload_from_stream(filename, async_file, Some(opt))
+            .await?;
         Ok(oid)
     }
+
+    async fn upload_from_resumable(
+        &mut self,
+        filename: &str,
+        local_path: impl AsRef<Path> + Send,
+    ) -> Result<ObjectId> {
+        let local_path = local_path.as_ref();
+        let expected = format!("{:x}", md5::compute(tokio::fs::read(local_path).await?));
+        let baseline = self.id_by_revision(filename, -1).await.ok();
+
+        let mut delay = INITIAL_BACKOFF;
+        let mut last_err = None;
+        for attempt in 0..MAX_UPLOAD_ATTEMPTS {
+            if attempt > 0 {
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+
+            match self.upload_from(filename, local_path).await {
+                Ok(oid) => return verify_checksum(self, oid, &expected).await,
+                Err(GridFSExtError::MongoError(err)) if is_transient(&err) => {
+                    cleanup_failed_attempt(self, filename, baseline).await?;
+                    last_err = Some(GridFSExtError::MongoError(err));
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Err(last_err.expect("loop runs at least once"))
+    }
+}
+
+/// Verify the just-uploaded doc `id`'s stored `md5` against `expected`, deleting it and
+/// returning [`crate::error::GridFSError::ChecksumMismatch`] on a mismatch.
+async fn verify_checksum(
+    bucket: &mut GridFSBucket,
+    id: ObjectId,
+    expected: &str,
+) -> Result<ObjectId> {
+    let actual = bucket.md5(id).await?;
+    if actual != expected {
+        bucket.delete(id).await?;
+        return Err(GridFSError::ChecksumMismatch {
+            expected: expected.to_owned(),
+            actual,
+        }
+        .into());
+    }
+    Ok(id)
+}
+
+/// Delete the orphaned doc a failed [`FileSync::upload_from_resumable`] attempt left
+/// behind for `filename`, if it created one: whatever now sorts newest, as long as it's
+/// not `baseline` (the newest doc that already existed for `filename` before this
+/// resumable upload started). No-op if the attempt didn't get far enough to create a doc
+/// at all.
+///
+/// This infers the failed attempt's doc by revision position rather than tracking its id
+/// directly, so it assumes `filename` has a single writer: a concurrent upload to the
+/// same `filename` racing with this retry loop can make this delete that upload's doc
+/// instead of the partial one left behind here. Like the rest of this crate's
+/// revision-based APIs, it offers no protection against that.
+async fn cleanup_failed_attempt(
+    bucket: &mut GridFSBucket,
+    filename: &str,
+    baseline: Option<ObjectId>,
+) -> Result<()> {
+    match bucket.id_by_revision(filename, -1).await {
+        Ok(id) if Some(id) != baseline => bucket.delete(id).await,
+        _ => Ok(()),
+    }
+}
+
+/// True for Mongo errors that are safe to retry with backoff: network-level failures and
+/// ones the server itself tagged retryable. Anything else (auth failures, validation
+/// errors, and the like) is permanent and should fail [`FileSync::upload_from_resumable`]
+/// immediately instead of burning through retries.
+fn is_transient(err: &mongodb::error::Error) -> bool {
+    err.is_network_error()
+        || err.contains_label("RetryableWriteError")
+        || err.contains_label("RetryableReadError")
+}
+
+async fn stream_to_file(
+    bucket: &GridFSBucket,
+    id: ObjectId,
+    path: impl AsRef<Path>,
+) -> Result<()> {
+    let mut file = tokio::fs::File::create(path).await?;
+    let mut cursor = bucket.open_download_stream(id).await?;
+    while let Some(buffer) = cursor.next().await {
+        file.write_all(&buffer).await?;
+    }
+    Ok(())
+}
+
+/// Stream doc `id` to `local_path`, refusing to clobber an existing file unless
+/// `overwrite` is set, and never leaving a partial file behind on failure.
+async fn download_id_to(
+    bucket: &GridFSBucket,
+    id: ObjectId,
+    local_path: &Path,
+    overwrite: bool,
+) -> Result<()> {
+    atomic_write(local_path, overwrite, |temp_path| async move {
+        stream_to_file(bucket, id, temp_path).await
+    })
+    .await
+}
+
+/// Write to `local_path` atomically: refuse to clobber an existing file unless
+/// `overwrite` is set, write via `write` to a sibling `.part` temp path, then rename
+/// into place -- so a failure never leaves a partial or zero-length file behind.
+async fn atomic_write<F, Fut>(local_path: &Path, overwrite: bool, write: F) -> Result<()>
+where
+    F: FnOnce(PathBuf) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    if !overwrite && tokio::fs::try_exists(local_path).await? {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{} already exists", local_path.display()),
+        )
+        .into());
+    }
+
+    let mut temp_name = local_path.as_os_str().to_owned();
+    temp_name.push(".part");
+    let temp_path = PathBuf::from(temp_name);
+
+    match write(temp_path.clone()).await {
+        Ok(()) => {
+            tokio::fs::rename(&temp_path, local_path).await?;
+            Ok(())
+        }
+        Err(err) => {
+            let _ = tokio::fs::remove_file(&temp_path).await;
+            Err(err)
+        }
+    }
 }
 
 #[cfg(test)]
@@ -69,6 +311,8 @@ pub(crate) mod tests {
     use test_utilities::gridfs;
     use tokio;
 
+    use crate::error::GridFSExtError;
+
     use super::*;
 
     #[tokio::test]
@@ -101,6 +345,108 @@ pub(crate) mod tests {
         assert_eq!(content, temp_file.content.unwrap());
     }
 
+    #[tokio::test]
+    async fn test_upload_from_resumable_verifies_checksum() {
+        let mongo_handle = ContainerBuilder::new("mongo")
+            .bind_port_as_default(Some("0"), "27017")
+            .build_disposable()
+            .await;
+
+        let mongo_url = mongo_handle.url();
+
+        let faker = fs::TempFileFaker::new()
+            .kind(fs::TempFileKind::Text)
+            .include_content(true);
+        let temp_file = faker.fake::<fs::TempFile>();
+        let link: String = FileName().fake();
+
+        let mut bucket = Client::with_uri_str(mongo_url)
+            .await
+            .unwrap()
+            .database("testdb")
+            .clone()
+            .bucket(None);
+        let oid = bucket
+            .upload_from_resumable(&link, temp_file.path)
+            .await
+            .unwrap();
+
+        let expected = format!("{:x}", md5::compute(temp_file.content.unwrap()));
+        assert_eq!(expected, bucket.md5(oid).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_upload_from_resumable_checksum_mismatch_cleans_up() {
+        let mongo_handle = ContainerBuilder::new("mongo")
+            .bind_port_as_default(Some("0"), "27017")
+            .build_disposable()
+            .await;
+        let mongo_url = mongo_handle.url();
+
+        let mut bucket = Client::with_uri_str(mongo_url)
+            .await
+            .unwrap()
+            .database("testdb")
+            .clone()
+            .bucket(None);
+
+        let link: String = FileName().fake();
+        bucket.write_string(&link, "some content").await.unwrap();
+        let id = bucket.id(&link).await.unwrap();
+
+        let err = verify_checksum(&mut bucket, id, "not-the-real-digest")
+            .await
+            .unwrap_err();
+        match err {
+            GridFSExtError::GridFSError(GridFSError::ChecksumMismatch { .. }) => (),
+            _ => assert!(false, "Should return error [GridFSError(ChecksumMismatch)]"),
+        }
+        assert!(!bucket.exists(&link).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_failed_attempt_leaves_baseline_untouched() {
+        let mongo_handle = ContainerBuilder::new("mongo")
+            .bind_port_as_default(Some("0"), "27017")
+            .build_disposable()
+            .await;
+        let mongo_url = mongo_handle.url();
+
+        let mut bucket = Client::with_uri_str(mongo_url)
+            .await
+            .unwrap()
+            .database("testdb")
+            .clone()
+            .bucket(None);
+
+        let link: String = FileName().fake();
+        bucket
+            .write_string(&link, "already there before the retry loop started")
+            .await
+            .unwrap();
+        let baseline = bucket.id_by_revision(&link, -1).await.ok();
+
+        bucket
+            .write_string(&link, "leftover from a failed attempt")
+            .await
+            .unwrap();
+        let failed_attempt_id = bucket.id_by_revision(&link, -1).await.unwrap();
+        assert_ne!(Some(failed_attempt_id), baseline);
+
+        cleanup_failed_attempt(&mut bucket, &link, baseline)
+            .await
+            .unwrap();
+
+        assert!(bucket.find_one_by_id(failed_attempt_id).await.is_err());
+        assert_eq!(baseline, bucket.id_by_revision(&link, -1).await.ok());
+
+        // Calling it again with no new doc created since is a no-op.
+        cleanup_failed_attempt(&mut bucket, &link, baseline)
+            .await
+            .unwrap();
+        assert_eq!(baseline, bucket.id_by_revision(&link, -1).await.ok());
+    }
+
     #[tokio::test]
     async fn test_download() {
         let mongo_handle = ContainerBuilder::new("mongo")
@@ -126,18 +472,199 @@ pub(crate) mod tests {
         assert_eq!(temp_file.filename.unwrap(), link);
 
         let local_download_path = NamedTempFile::new().unwrap().into_temp_path();
+        tokio::fs::remove_file(&local_download_path).await.unwrap();
         let ret_oid = bucket
             .download_to(&link, &local_download_path)
             .await
             .unwrap();
         assert_eq!(temp_file.id, ret_oid);
 
-        let download_doc = tokio::fs::read_to_string(local_download_path)
+        let download_doc = tokio::fs::read_to_string(&local_download_path)
             .await
             .unwrap();
         assert_eq!(temp_file.content.unwrap(), download_doc.into_bytes());
     }
 
+    #[tokio::test]
+    async fn test_download_refuses_to_clobber_existing_file() {
+        let mongo_handle = ContainerBuilder::new("mongo")
+            .bind_port_as_default(Some("0"), "27017")
+            .build_disposable()
+            .await;
+        let mongo_url = mongo_handle.url();
+        let bucket = Client::with_uri_str(mongo_url)
+            .await
+            .unwrap()
+            .database("testdb")
+            .clone()
+            .bucket(None);
+
+        let link: String = FileName().fake();
+        let faker = gridfs::TempFileFaker::with_bucket(bucket.clone())
+            .kind(fs::TempFileKind::Text)
+            .len(50..100)
+            .include_content(true)
+            .name(link.clone());
+        let temp_file = faker.fake::<gridfs::TempFile>();
+
+        let local_download_path = NamedTempFile::new().unwrap().into_temp_path();
+        let err = bucket
+            .download_to(&link, &local_download_path)
+            .await
+            .unwrap_err();
+        match err {
+            GridFSExtError::IOError(err) => {
+                assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists)
+            }
+            _ => assert!(false, "Should return error [IOError(AlreadyExists)]"),
+        }
+
+        let ret_oid = bucket
+            .download_to_with_options(&link, &local_download_path, true)
+            .await
+            .unwrap();
+        assert_eq!(temp_file.id, ret_oid);
+    }
+
+    #[tokio::test]
+    async fn test_download_missing_file_creates_no_local_file() {
+        let mongo_handle = ContainerBuilder::new("mongo")
+            .bind_port_as_default(Some("0"), "27017")
+            .build_disposable()
+            .await;
+        let mongo_url = mongo_handle.url();
+        let bucket = Client::with_uri_str(mongo_url)
+            .await
+            .unwrap()
+            .database("testdb")
+            .clone()
+            .bucket(None);
+
+        let local_download_path = NamedTempFile::new().unwrap().into_temp_path();
+        tokio::fs::remove_file(&local_download_path).await.unwrap();
+
+        let link: String = FileName().fake();
+        assert!(bucket.download_to(&link, &local_download_path).await.is_err());
+        assert!(!tokio::fs::try_exists(&local_download_path).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_download_range_to() {
+        let mongo_handle = ContainerBuilder::new("mongo")
+            .bind_port_as_default(Some("0"), "27017")
+            .build_disposable()
+            .await;
+        let mongo_url = mongo_handle.url();
+        let bucket = Client::with_uri_str(mongo_url)
+            .await
+            .unwrap()
+            .database("testdb")
+            .clone()
+            .bucket(None);
+
+        let link: String = FileName().fake();
+        let faker = gridfs::TempFileFaker::with_bucket(bucket.clone())
+            .kind(fs::TempFileKind::Text)
+            .len(50..100)
+            .include_content(true)
+            .name(link.clone());
+        let temp_file = faker.fake::<gridfs::TempFile>();
+        let content = temp_file.content.unwrap();
+
+        let local_download_path = NamedTempFile::new().unwrap().into_temp_path();
+        tokio::fs::remove_file(&local_download_path).await.unwrap();
+        let ret_oid = bucket
+            .download_range_to(&link, 10, 20, &local_download_path)
+            .await
+            .unwrap();
+        assert_eq!(temp_file.id, ret_oid);
+
+        let downloaded = tokio::fs::read(&local_download_path).await.unwrap();
+        assert_eq!(content[10..20].to_vec(), downloaded);
+    }
+
+    #[tokio::test]
+    async fn test_download_range_to_refuses_to_clobber_existing_file() {
+        let mongo_handle = ContainerBuilder::new("mongo")
+            .bind_port_as_default(Some("0"), "27017")
+            .build_disposable()
+            .await;
+        let mongo_url = mongo_handle.url();
+        let bucket = Client::with_uri_str(mongo_url)
+            .await
+            .unwrap()
+            .database("testdb")
+            .clone()
+            .bucket(None);
+
+        let link: String = FileName().fake();
+        let faker = gridfs::TempFileFaker::with_bucket(bucket.clone())
+            .kind(fs::TempFileKind::Text)
+            .len(50..100)
+            .include_content(true)
+            .name(link.clone());
+        let _temp_file = faker.fake::<gridfs::TempFile>();
+
+        let local_download_path = NamedTempFile::new().unwrap().into_temp_path();
+        let err = bucket
+            .download_range_to(&link, 10, 20, &local_download_path)
+            .await
+            .unwrap_err();
+        match err {
+            GridFSExtError::IOError(err) => {
+                assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists)
+            }
+            _ => assert!(false, "Should return error [IOError(AlreadyExists)]"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_download_to_by_revision() {
+        let mongo_handle = ContainerBuilder::new("mongo")
+            .bind_port_as_default(Some("0"), "27017")
+            .build_disposable()
+            .await;
+        let mongo_url = mongo_handle.url();
+        let mut bucket = Client::with_uri_str(mongo_url)
+            .await
+            .unwrap()
+            .database("testdb")
+            .clone()
+            .bucket(None);
+
+        let link: String = FileName().fake();
+        bucket.write_string(&link, "oldest").await.unwrap();
+        bucket.write_string(&link, "middle").await.unwrap();
+        bucket.write_string(&link, "newest").await.unwrap();
+
+        let oldest_oid = bucket.id_by_revision(&link, 0).await.unwrap();
+        let newest_oid = bucket.id_by_revision(&link, -1).await.unwrap();
+
+        let oldest_path = NamedTempFile::new().unwrap().into_temp_path();
+        tokio::fs::remove_file(&oldest_path).await.unwrap();
+        let ret_oid = bucket
+            .download_to_by_revision(&link, 0, &oldest_path)
+            .await
+            .unwrap();
+        assert_eq!(oldest_oid, ret_oid);
+        assert_eq!(
+            "oldest",
+            tokio::fs::read_to_string(&oldest_path).await.unwrap()
+        );
+
+        let newest_path = NamedTempFile::new().unwrap().into_temp_path();
+        tokio::fs::remove_file(&newest_path).await.unwrap();
+        let ret_oid = bucket
+            .download_to_by_revision(&link, -1, &newest_path)
+            .await
+            .unwrap();
+        assert_eq!(newest_oid, ret_oid);
+        assert_eq!(
+            "newest",
+            tokio::fs::read_to_string(&newest_path).await.unwrap()
+        );
+    }
+
     #[tokio::test]
     async fn test_download_big_file() {
         let mongo_handle = ContainerBuilder::new("mongo")
@@ -163,13 +690,14 @@ pub(crate) mod tests {
         assert_eq!(temp_file.filename.unwrap(), link);
 
         let local_download_path = NamedTempFile::new().unwrap().into_temp_path();
+        tokio::fs::remove_file(&local_download_path).await.unwrap();
         let ret_oid = bucket
             .download_to(&link, &local_download_path)
             .await
             .unwrap();
         assert_eq!(temp_file.id, ret_oid);
 
-        let download_doc = tokio::fs::read_to_string(local_download_path)
+        let download_doc = tokio::fs::read_to_string(&local_download_path)
             .await
             .unwrap();
 
@@ -0,0 +1,34 @@
+use mongodb::bson::{doc, Document};
+
+/// How many leading bytes of a file to read when sniffing its MIME type.
+pub(crate) const SNIFF_LEN: usize = 8192;
+
+/// Well-known magic numbers, checked in order against the start of a file's bytes.
+const SIGNATURES: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1f\x8b", "application/gzip"),
+];
+
+/// Detect the MIME type of `bytes` from its leading magic number, falling back to
+/// `application/octet-stream` when nothing matches.
+pub(crate) fn sniff(bytes: &[u8]) -> &'static str {
+    SIGNATURES
+        .iter()
+        .find(|(signature, _)| bytes.starts_with(signature))
+        .map(|(_, content_type)| *content_type)
+        .unwrap_or("application/octet-stream")
+}
+
+/// Build the `metadata` document stashed on a GridFS files doc at upload time.
+pub(crate) fn metadata_doc(content_type: &str, extension: Option<&str>) -> Document {
+    let mut metadata = doc! { "contentType": content_type };
+    if let Some(extension) = extension {
+        metadata.insert("extension", extension);
+    }
+    metadata
+}
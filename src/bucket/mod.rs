@@ -0,0 +1,4 @@
+pub mod common;
+pub mod file_sync;
+pub(crate) mod mime;
+pub mod stream;
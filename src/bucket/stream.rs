@@ -0,0 +1,228 @@
+use std::collections::VecDeque;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::Stream;
+use mongodb::bson::oid::ObjectId;
+use mongodb_gridfs::options::GridFSUploadOptions;
+use mongodb_gridfs::GridFSBucket;
+use tokio::io::{AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::task::JoinHandle;
+use tokio_util::compat::TokioAsyncReadCompatExt;
+
+use crate::bucket::mime::{self, SNIFF_LEN};
+use crate::error::Result;
+
+/// A [`tokio::io::AsyncRead`] over a GridFS file's chunks, streamed as they arrive
+/// instead of being buffered fully in memory.
+pub struct GridFSAsyncReader {
+    chunks: Pin<Box<dyn Stream<Item = Vec<u8>> + Send>>,
+    buf: VecDeque<u8>,
+}
+
+impl GridFSAsyncReader {
+    pub(crate) fn new(chunks: Pin<Box<dyn Stream<Item = Vec<u8>> + Send>>) -> Self {
+        Self {
+            chunks,
+            buf: VecDeque::new(),
+        }
+    }
+}
+
+impl tokio::io::AsyncRead for GridFSAsyncReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        loop {
+            if !self.buf.is_empty() {
+                let n = buf.remaining().min(self.buf.len());
+                let chunk: Vec<u8> = self.buf.drain(..n).collect();
+                buf.put_slice(&chunk);
+                return Poll::Ready(Ok(()));
+            }
+
+            match self.chunks.as_mut().poll_next(cx) {
+                Poll::Ready(Some(chunk)) => self.buf.extend(chunk),
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// The upload hasn't started yet: bytes are buffered so the first [`SNIFF_LEN`] of them
+/// can be used to detect a content type, the same way [`crate::FileSync::upload_from`]
+/// does for local files.
+struct Buffering {
+    bucket: GridFSBucket,
+    filename: String,
+    sniff_buf: Vec<u8>,
+}
+
+/// The upload has started: bytes are written straight into the duplex stream backing it.
+/// `prefix` is the sniffed bytes carried over from [`Buffering`], still waiting to be
+/// forwarded into `sink`.
+struct Streaming {
+    sink: tokio::io::DuplexStream,
+    upload: JoinHandle<Result<ObjectId>>,
+    prefix: Vec<u8>,
+    prefix_sent: usize,
+}
+
+enum State {
+    Buffering(Buffering),
+    Streaming(Streaming),
+}
+
+impl Buffering {
+    /// Sniff a content type from whatever's been buffered so far and start the real
+    /// upload, carrying the buffered bytes over as a prefix still to be written.
+    fn into_streaming(self) -> Streaming {
+        let Buffering {
+            mut bucket,
+            filename,
+            sniff_buf,
+        } = self;
+
+        let content_type = mime::sniff(&sniff_buf);
+        let extension = Path::new(&filename)
+            .extension()
+            .and_then(|ext| ext.to_str());
+        let opt = GridFSUploadOptions {
+            metadata: Some(mime::metadata_doc(content_type, extension)),
+            ..Default::default()
+        };
+
+        let (sink, source) = tokio::io::duplex(64 * 1024);
+        let upload = tokio::spawn(async move {
+            bucket
+                .upload_from_stream(&filename, source.compat(), Some(opt))
+                .await
+                .map_err(Into::into)
+        });
+
+        Streaming {
+            sink,
+            upload,
+            prefix: sniff_buf,
+            prefix_sent: 0,
+        }
+    }
+}
+
+impl Streaming {
+    /// Forward whatever of `prefix` hasn't been written into `sink` yet.
+    fn poll_flush_prefix(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        while self.prefix_sent < self.prefix.len() {
+            match Pin::new(&mut self.sink).poll_write(cx, &self.prefix[self.prefix_sent..]) {
+                Poll::Ready(Ok(written)) => self.prefix_sent += written,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// A [`tokio::io::AsyncWrite`] that streams written bytes straight into a GridFS upload,
+/// without buffering the whole payload in memory. The first [`SNIFF_LEN`] bytes written
+/// (or everything written, if fewer) are sniffed for a content type before the upload
+/// starts, so the resulting doc gets the same `metadata.contentType`/`extension` as
+/// [`crate::FileSync::upload_from`] attaches. Call [`GridFSAsyncWriter::finish`] once done
+/// writing to flush the upload and get the new document's id.
+pub struct GridFSAsyncWriter {
+    state: Option<State>,
+}
+
+impl GridFSAsyncWriter {
+    pub(crate) fn new(bucket: GridFSBucket, filename: String) -> Self {
+        Self {
+            state: Some(State::Buffering(Buffering {
+                bucket,
+                filename,
+                sniff_buf: Vec::new(),
+            })),
+        }
+    }
+
+    /// Start the real upload from whatever has been buffered so far, if it hasn't
+    /// started already.
+    fn ensure_streaming(&mut self) -> &mut Streaming {
+        let state = match self.state.take().expect("state is always put back") {
+            State::Buffering(buffering) => State::Streaming(buffering.into_streaming()),
+            streaming @ State::Streaming(_) => streaming,
+        };
+        match self.state.insert(state) {
+            State::Streaming(streaming) => streaming,
+            State::Buffering(_) => unreachable!("just transitioned to Streaming"),
+        }
+    }
+
+    /// Flush and close the upload, returning the id of the uploaded document.
+    pub async fn finish(mut self) -> Result<ObjectId> {
+        self.shutdown().await?;
+        let Some(State::Streaming(Streaming { sink, upload, .. })) = self.state else {
+            unreachable!("poll_shutdown transitions state")
+        };
+        drop(sink);
+        match upload.await {
+            Ok(result) => result,
+            Err(err) => Err(std::io::Error::new(std::io::ErrorKind::Other, err).into()),
+        }
+    }
+}
+
+impl AsyncWrite for GridFSAsyncWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let prefix_len = if let Some(State::Buffering(buffering)) = &mut self.state {
+            let remaining = SNIFF_LEN - buffering.sniff_buf.len();
+            let n = remaining.min(buf.len());
+            buffering.sniff_buf.extend_from_slice(&buf[..n]);
+            if buffering.sniff_buf.len() < SNIFF_LEN {
+                return Poll::Ready(Ok(n));
+            }
+            n
+        } else {
+            0
+        };
+
+        let streaming = self.get_mut().ensure_streaming();
+        if let Poll::Pending = streaming.poll_flush_prefix(cx) {
+            return if prefix_len > 0 {
+                Poll::Ready(Ok(prefix_len))
+            } else {
+                Poll::Pending
+            };
+        }
+
+        match Pin::new(&mut streaming.sink).poll_write(cx, &buf[prefix_len..]) {
+            Poll::Ready(Ok(written)) => Poll::Ready(Ok(prefix_len + written)),
+            Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+            Poll::Pending if prefix_len > 0 => Poll::Ready(Ok(prefix_len)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        let streaming = self.get_mut().ensure_streaming();
+        match streaming.poll_flush_prefix(cx) {
+            Poll::Ready(Ok(())) => Pin::new(&mut streaming.sink).poll_flush(cx),
+            other => other,
+        }
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        let streaming = self.get_mut().ensure_streaming();
+        match streaming.poll_flush_prefix(cx) {
+            Poll::Ready(Ok(())) => Pin::new(&mut streaming.sink).poll_shutdown(cx),
+            other => other,
+        }
+    }
+}
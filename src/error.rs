@@ -9,6 +9,18 @@ pub enum GridFSError {
         filename: Option<String>,
         id: Option<ObjectId>,
     },
+    InvalidPartialDownloadRange {
+        start: u64,
+        end: u64,
+    },
+    PartialDownloadRangeOutOfBounds {
+        value: u64,
+        file_length: u64,
+    },
+    ChecksumMismatch {
+        expected: String,
+        actual: String,
+    },
 }
 
 impl Display for GridFSError {
@@ -20,6 +32,21 @@ impl Display for GridFSError {
             } => write!(f, "FileNotFound(filename={})", filename),
             Self::FileNotFound { id: Some(id), .. } => write!(f, "FileNotFound(id={})", id),
             Self::FileNotFound { .. } => write!(f, "FileNotFound(None)"),
+            Self::InvalidPartialDownloadRange { start, end } => write!(
+                f,
+                "InvalidPartialDownloadRange(start={}, end={})",
+                start, end
+            ),
+            Self::PartialDownloadRangeOutOfBounds { value, file_length } => write!(
+                f,
+                "PartialDownloadRangeOutOfBounds(value={}, file_length={})",
+                value, file_length
+            ),
+            Self::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "ChecksumMismatch(expected={}, actual={})",
+                expected, actual
+            ),
         }
     }
 }
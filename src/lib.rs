@@ -0,0 +1,8 @@
+//! Extension traits for [`mongodb_gridfs::GridFSBucket`].
+
+pub mod bucket;
+pub mod error;
+
+pub use bucket::common::GridFSBucketExt;
+pub use bucket::file_sync::FileSync;
+pub use bucket::stream::{GridFSAsyncReader, GridFSAsyncWriter};